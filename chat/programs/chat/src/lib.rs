@@ -2,6 +2,31 @@ use anchor_lang::prelude::*;
 
 declare_id!("CqDSGGxfagLcVq8KYQjz4iRnPRxVkVSpVp2bnj19nHVj");
 
+/// Maximum length, in bytes, of a message body.
+pub const MAX_MESSAGE_LEN: usize = 280;
+/// Maximum length, in bytes, of a channel name.
+pub const MAX_CHANNEL_NAME_LEN: usize = 32;
+/// Maximum length, in bytes, of a dialogue branch id or label.
+pub const MAX_LABEL_LEN: usize = 32;
+/// Maximum length, in bytes, of a dialogue branch reply.
+pub const MAX_REPLY_LEN: usize = 280;
+/// Sentinel `goto` label that ends a dialogue script.
+pub const EXIT_LABEL: &str = "EXIT";
+/// Conventional `label` for the first branch of a dialogue script.
+pub const INIT_LABEL: &str = "INIT";
+/// Maximum length, in bytes, of a profile display name.
+pub const MAX_DISPLAY_NAME_LEN: usize = 32;
+/// Maximum length, in bytes, of a profile pronoun.
+pub const MAX_PRONOUN_LEN: usize = 16;
+/// Maximum length, in bytes, of a profile bio.
+pub const MAX_BIO_LEN: usize = 160;
+/// Pronoun assigned to a `Profile` until the owner sets one explicitly.
+pub const DEFAULT_PRONOUN: &str = "they/them";
+/// Maximum number of messages a member may post within `RATE_LIMIT_WINDOW_SECS`.
+pub const MAX_POSTS_PER_WINDOW: u16 = 5;
+/// Length, in seconds, of the rate-limit window enforced in `send_message`.
+pub const RATE_LIMIT_WINDOW_SECS: i64 = 60;
+
 #[program]
 pub mod chat {
     use super::*;
@@ -10,7 +35,647 @@ pub mod chat {
         msg!("Greetings from: {:?}", ctx.program_id);
         Ok(())
     }
+
+    pub fn create_channel(ctx: Context<CreateChannel>, name: String) -> Result<()> {
+        require!(name.len() <= MAX_CHANNEL_NAME_LEN, ChatError::NameTooLong);
+
+        let channel = &mut ctx.accounts.channel;
+        channel.name = name;
+        channel.creator = ctx.accounts.user.key();
+        channel.authority = ctx.accounts.user.key();
+        channel.message_count = 0;
+        channel.bump = ctx.bumps.channel;
+
+        emit!(ChannelCreated {
+            channel: channel.key(),
+            creator: channel.creator,
+            name: channel.name.clone(),
+        });
+
+        Ok(())
+    }
+
+    pub fn update_channel_name(ctx: Context<UpdateChannelName>, name: String) -> Result<()> {
+        require!(name.len() <= MAX_CHANNEL_NAME_LEN, ChatError::NameTooLong);
+        ctx.accounts.channel.name = name;
+        Ok(())
+    }
+
+    pub fn transfer_authority(
+        ctx: Context<TransferAuthority>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.channel.authority = new_authority;
+        Ok(())
+    }
+
+    pub fn close_channel(_ctx: Context<CloseChannel>) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn create_profile(
+        ctx: Context<CreateProfile>,
+        display_name: String,
+        bio: String,
+    ) -> Result<()> {
+        require!(
+            display_name.len() <= MAX_DISPLAY_NAME_LEN,
+            ChatError::DisplayNameTooLong
+        );
+        require!(bio.len() <= MAX_BIO_LEN, ChatError::BioTooLong);
+
+        let profile = &mut ctx.accounts.profile;
+        profile.owner = ctx.accounts.owner.key();
+        profile.display_name = display_name;
+        profile.pronoun = DEFAULT_PRONOUN.to_string();
+        profile.bio = bio;
+        profile.bump = ctx.bumps.profile;
+
+        Ok(())
+    }
+
+    pub fn update_profile(
+        ctx: Context<UpdateProfile>,
+        display_name: Option<String>,
+        pronoun: Option<String>,
+        bio: Option<String>,
+    ) -> Result<()> {
+        let profile = &mut ctx.accounts.profile;
+
+        if let Some(display_name) = display_name {
+            require!(
+                display_name.len() <= MAX_DISPLAY_NAME_LEN,
+                ChatError::DisplayNameTooLong
+            );
+            profile.display_name = display_name;
+        }
+
+        if let Some(pronoun) = pronoun {
+            require!(pronoun.len() <= MAX_PRONOUN_LEN, ChatError::PronounTooLong);
+            profile.pronoun = pronoun;
+        }
+
+        if let Some(bio) = bio {
+            require!(bio.len() <= MAX_BIO_LEN, ChatError::BioTooLong);
+            profile.bio = bio;
+        }
+
+        Ok(())
+    }
+
+    pub fn join_channel(ctx: Context<JoinChannel>) -> Result<()> {
+        let membership = &mut ctx.accounts.membership;
+        membership.channel = ctx.accounts.channel.key();
+        membership.user = ctx.accounts.user.key();
+        membership.last_post_ts = 0;
+        membership.window_start = 0;
+        membership.posts_in_window = 0;
+        membership.bump = ctx.bumps.membership;
+
+        emit!(MembershipJoined {
+            channel: membership.channel,
+            user: membership.user,
+        });
+
+        Ok(())
+    }
+
+    pub fn send_message(ctx: Context<SendMessage>, body: String) -> Result<()> {
+        require!(body.len() <= MAX_MESSAGE_LEN, ChatError::MessageTooLong);
+
+        let now = Clock::get()?.unix_timestamp;
+        let membership = &mut ctx.accounts.membership;
+        if now - membership.window_start >= RATE_LIMIT_WINDOW_SECS {
+            membership.window_start = now;
+            membership.posts_in_window = 0;
+        }
+        require!(
+            membership.posts_in_window < MAX_POSTS_PER_WINDOW,
+            ChatError::RateLimited
+        );
+        membership.posts_in_window += 1;
+        membership.last_post_ts = now;
+
+        let channel = &mut ctx.accounts.channel;
+
+        let message = &mut ctx.accounts.message;
+        message.author = ctx.accounts.author.key();
+        message.channel = channel.key();
+        message.timestamp = now;
+        message.body = body;
+
+        channel.message_count = channel
+            .message_count
+            .checked_add(1)
+            .ok_or(ChatError::MessageCountOverflow)?;
+
+        emit!(MessageSent {
+            channel: channel.key(),
+            author: message.author,
+            sequence: channel.message_count - 1,
+            timestamp: message.timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn open_dm(ctx: Context<OpenDm>, other: Pubkey) -> Result<()> {
+        let (participant_a, participant_b) = sorted_pubkeys(ctx.accounts.initializer.key(), other);
+
+        let conversation = &mut ctx.accounts.conversation;
+        conversation.participant_a = participant_a;
+        conversation.participant_b = participant_b;
+        conversation.message_count = 0;
+        conversation.bump = ctx.bumps.conversation;
+
+        Ok(())
+    }
+
+    pub fn send_dm(ctx: Context<SendDm>, body: String) -> Result<()> {
+        require!(body.len() <= MAX_MESSAGE_LEN, ChatError::MessageTooLong);
+
+        let conversation = &mut ctx.accounts.conversation;
+        let sender = ctx.accounts.sender.key();
+        require!(
+            sender == conversation.participant_a || sender == conversation.participant_b,
+            ChatError::NotAParticipant
+        );
+
+        let dm = &mut ctx.accounts.direct_message;
+        dm.author = sender;
+        dm.conversation = conversation.key();
+        dm.timestamp = Clock::get()?.unix_timestamp;
+        dm.body = body;
+
+        conversation.message_count = conversation
+            .message_count
+            .checked_add(1)
+            .ok_or(ChatError::MessageCountOverflow)?;
+
+        emit!(DmSent {
+            conversation: conversation.key(),
+            author: dm.author,
+            sequence: conversation.message_count - 1,
+            timestamp: dm.timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_script(ctx: Context<CreateScript>) -> Result<()> {
+        let script = &mut ctx.accounts.script;
+        script.author = ctx.accounts.author.key();
+        script.bump = ctx.bumps.script;
+
+        Ok(())
+    }
+
+    pub fn create_branch(
+        ctx: Context<CreateBranch>,
+        id: String,
+        label: String,
+        delay: u32,
+        reply: String,
+        goto: String,
+    ) -> Result<()> {
+        require!(id.len() <= MAX_LABEL_LEN, ChatError::LabelTooLong);
+        require!(label.len() <= MAX_LABEL_LEN, ChatError::LabelTooLong);
+        require!(goto.len() <= MAX_LABEL_LEN, ChatError::LabelTooLong);
+        require!(reply.len() <= MAX_REPLY_LEN, ChatError::ReplyTooLong);
+
+        let branch = &mut ctx.accounts.branch;
+        branch.id = id;
+        branch.label = label;
+        branch.delay = delay;
+        branch.reply = reply;
+        branch.goto = goto;
+        branch.bump = ctx.bumps.branch;
+
+        Ok(())
+    }
+
+    pub fn advance(ctx: Context<Advance>, _current_label: String) -> Result<()> {
+        let branch = &ctx.accounts.branch;
+
+        emit!(DialogueAdvanced {
+            script: ctx.accounts.script.key(),
+            label: branch.label.clone(),
+            reply: branch.reply.clone(),
+            goto: branch.goto.clone(),
+        });
+
+        require!(
+            branch.goto == EXIT_LABEL || ctx.accounts.next_branch.is_some(),
+            ChatError::UnknownGotoLabel
+        );
+
+        Ok(())
+    }
+}
+
+/// Orders two participant pubkeys so a conversation's PDA is independent of
+/// who opens it: the lexicographically smaller key is always `.0`.
+fn sorted_pubkeys(a: Pubkey, b: Pubkey) -> (Pubkey, Pubkey) {
+    if a.to_bytes() <= b.to_bytes() {
+        (a, b)
+    } else {
+        (b, a)
+    }
 }
 
 #[derive(Accounts)]
 pub struct Initialize {}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct CreateChannel<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Channel::INIT_SPACE,
+        seeds = [b"channel", user.key().as_ref(), name.as_bytes()],
+        bump
+    )]
+    pub channel: Account<'info, Channel>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateChannelName<'info> {
+    #[account(mut, has_one = authority @ ChatError::Unauthorized)]
+    pub channel: Account<'info, Channel>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TransferAuthority<'info> {
+    #[account(mut, has_one = authority @ ChatError::Unauthorized)]
+    pub channel: Account<'info, Channel>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseChannel<'info> {
+    #[account(mut, has_one = authority @ ChatError::Unauthorized, close = authority)]
+    pub channel: Account<'info, Channel>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SendMessage<'info> {
+    #[account(mut)]
+    pub channel: Account<'info, Channel>,
+
+    #[account(
+        init,
+        payer = author,
+        space = 8 + Message::INIT_SPACE,
+        seeds = [b"msg", channel.key().as_ref(), &channel.message_count.to_le_bytes()],
+        bump
+    )]
+    pub message: Account<'info, Message>,
+
+    #[account(constraint = profile.owner == author.key() @ ChatError::NotProfileOwner)]
+    pub profile: Account<'info, Profile>,
+
+    #[account(
+        mut,
+        seeds = [b"member", channel.key().as_ref(), author.key().as_ref()],
+        bump = membership.bump
+    )]
+    pub membership: Account<'info, Membership>,
+
+    #[account(mut)]
+    pub author: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinChannel<'info> {
+    pub channel: Account<'info, Channel>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Membership::INIT_SPACE,
+        seeds = [b"member", channel.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub membership: Account<'info, Membership>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateProfile<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Profile::INIT_SPACE,
+        seeds = [b"profile", owner.key().as_ref()],
+        bump
+    )]
+    pub profile: Account<'info, Profile>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProfile<'info> {
+    #[account(mut, has_one = owner @ ChatError::NotProfileOwner)]
+    pub profile: Account<'info, Profile>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(other: Pubkey)]
+pub struct OpenDm<'info> {
+    #[account(
+        init,
+        payer = initializer,
+        space = 8 + Conversation::INIT_SPACE,
+        seeds = [
+            b"dm",
+            sorted_pubkeys(initializer.key(), other).0.as_ref(),
+            sorted_pubkeys(initializer.key(), other).1.as_ref()
+        ],
+        bump
+    )]
+    pub conversation: Account<'info, Conversation>,
+
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SendDm<'info> {
+    #[account(
+        mut,
+        seeds = [b"dm", conversation.participant_a.as_ref(), conversation.participant_b.as_ref()],
+        bump = conversation.bump
+    )]
+    pub conversation: Account<'info, Conversation>,
+
+    #[account(
+        init,
+        payer = sender,
+        space = 8 + DirectMessage::INIT_SPACE,
+        seeds = [b"dm_msg", conversation.key().as_ref(), &conversation.message_count.to_le_bytes()],
+        bump
+    )]
+    pub direct_message: Account<'info, DirectMessage>,
+
+    #[account(mut)]
+    pub sender: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateScript<'info> {
+    #[account(
+        init,
+        payer = author,
+        space = 8 + DialogueScript::INIT_SPACE,
+        seeds = [b"script", author.key().as_ref()],
+        bump
+    )]
+    pub script: Account<'info, DialogueScript>,
+
+    #[account(mut)]
+    pub author: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: String, label: String)]
+pub struct CreateBranch<'info> {
+    #[account(has_one = author @ ChatError::NotScriptAuthor)]
+    pub script: Account<'info, DialogueScript>,
+
+    #[account(
+        init,
+        payer = author,
+        space = 8 + ChatBranch::INIT_SPACE,
+        seeds = [b"branch", script.key().as_ref(), label.as_bytes()],
+        bump
+    )]
+    pub branch: Account<'info, ChatBranch>,
+
+    #[account(mut)]
+    pub author: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(current_label: String)]
+pub struct Advance<'info> {
+    pub script: Account<'info, DialogueScript>,
+
+    #[account(
+        seeds = [b"branch", script.key().as_ref(), current_label.as_bytes()],
+        bump = branch.bump
+    )]
+    pub branch: Account<'info, ChatBranch>,
+
+    #[account(
+        seeds = [b"branch", script.key().as_ref(), branch.goto.as_bytes()],
+        bump
+    )]
+    pub next_branch: Option<Account<'info, ChatBranch>>,
+}
+
+/// A public, append-only chat channel.
+#[account]
+#[derive(InitSpace)]
+pub struct Channel {
+    #[max_len(MAX_CHANNEL_NAME_LEN)]
+    pub name: String,
+    pub creator: Pubkey,
+    pub authority: Pubkey,
+    pub message_count: u64,
+    pub bump: u8,
+}
+
+/// A single message posted to a `Channel`, addressed by its sequence number.
+#[account]
+#[derive(InitSpace)]
+pub struct Message {
+    pub author: Pubkey,
+    pub channel: Pubkey,
+    pub timestamp: i64,
+    #[max_len(MAX_MESSAGE_LEN)]
+    pub body: String,
+}
+
+/// A single canonical 1:1 conversation between two participants.
+#[account]
+#[derive(InitSpace)]
+pub struct Conversation {
+    pub participant_a: Pubkey,
+    pub participant_b: Pubkey,
+    pub message_count: u64,
+    pub bump: u8,
+}
+
+/// A single message posted within a `Conversation`, addressed by its sequence number.
+#[account]
+#[derive(InitSpace)]
+pub struct DirectMessage {
+    pub author: Pubkey,
+    pub conversation: Pubkey,
+    pub timestamp: i64,
+    #[max_len(MAX_MESSAGE_LEN)]
+    pub body: String,
+}
+
+/// The root account for a branching, on-chain dialogue script.
+#[account]
+#[derive(InitSpace)]
+pub struct DialogueScript {
+    pub author: Pubkey,
+    pub bump: u8,
+}
+
+/// A single node of a `DialogueScript`, keyed by its `label`.
+#[account]
+#[derive(InitSpace)]
+pub struct ChatBranch {
+    #[max_len(MAX_LABEL_LEN)]
+    pub id: String,
+    #[max_len(MAX_LABEL_LEN)]
+    pub label: String,
+    pub delay: u32,
+    #[max_len(MAX_REPLY_LEN)]
+    pub reply: String,
+    /// Either `EXIT` or the `label` of the next branch in the script.
+    #[max_len(MAX_LABEL_LEN)]
+    pub goto: String,
+    pub bump: u8,
+}
+
+/// A user's on-chain identity, resolved when rendering their messages.
+#[account]
+#[derive(InitSpace)]
+pub struct Profile {
+    pub owner: Pubkey,
+    #[max_len(MAX_DISPLAY_NAME_LEN)]
+    pub display_name: String,
+    #[max_len(MAX_PRONOUN_LEN)]
+    pub pronoun: String,
+    #[max_len(MAX_BIO_LEN)]
+    pub bio: String,
+    pub bump: u8,
+}
+
+/// A user's membership in a `Channel`, and their rate-limit bucket for posting.
+#[account]
+#[derive(InitSpace)]
+pub struct Membership {
+    pub channel: Pubkey,
+    pub user: Pubkey,
+    pub last_post_ts: i64,
+    /// Unix timestamp at which the current rate-limit window started.
+    pub window_start: i64,
+    pub posts_in_window: u16,
+    pub bump: u8,
+}
+
+/// Emitted when `join_channel` succeeds.
+#[event]
+pub struct MembershipJoined {
+    pub channel: Pubkey,
+    pub user: Pubkey,
+}
+
+/// Emitted when `create_channel` succeeds. A client subscribed via
+/// `logsSubscribe`/`onLogs` can use this to discover new channels without
+/// polling `getProgramAccounts`.
+#[event]
+pub struct ChannelCreated {
+    pub channel: Pubkey,
+    pub creator: Pubkey,
+    pub name: String,
+}
+
+/// Emitted when `send_message` succeeds. `sequence` is the zero-indexed
+/// position of the message within the channel (its `message_count` at the
+/// time of posting), matching the index used to derive the `Message` PDA.
+#[event]
+pub struct MessageSent {
+    pub channel: Pubkey,
+    pub author: Pubkey,
+    pub sequence: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted when `send_dm` succeeds. `sequence` is the zero-indexed position
+/// of the message within the conversation, matching the index used to
+/// derive the `DirectMessage` PDA.
+#[event]
+pub struct DmSent {
+    pub conversation: Pubkey,
+    pub author: Pubkey,
+    pub sequence: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DialogueAdvanced {
+    pub script: Pubkey,
+    pub label: String,
+    pub reply: String,
+    pub goto: String,
+}
+
+#[error_code]
+pub enum ChatError {
+    #[msg("channel name exceeds the maximum length")]
+    NameTooLong,
+    #[msg("message body exceeds the maximum length")]
+    MessageTooLong,
+    #[msg("channel or conversation message count overflowed")]
+    MessageCountOverflow,
+    #[msg("signer is not the channel authority")]
+    Unauthorized,
+    #[msg("signer is not a participant in this conversation")]
+    NotAParticipant,
+    #[msg("dialogue label exceeds the maximum length")]
+    LabelTooLong,
+    #[msg("dialogue reply exceeds the maximum length")]
+    ReplyTooLong,
+    #[msg("goto label does not resolve to EXIT or an existing branch")]
+    UnknownGotoLabel,
+    #[msg("signer is not the author of this dialogue script")]
+    NotScriptAuthor,
+    #[msg("signer is not the owner of this profile")]
+    NotProfileOwner,
+    #[msg("display name exceeds the maximum length")]
+    DisplayNameTooLong,
+    #[msg("pronoun exceeds the maximum length")]
+    PronounTooLong,
+    #[msg("bio exceeds the maximum length")]
+    BioTooLong,
+    #[msg("too many messages sent within the rate-limit window")]
+    RateLimited,
+}